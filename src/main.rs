@@ -12,45 +12,176 @@ extern crate clap;
 
 use std::io::{self, BufRead};
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
 use clap::{Arg, App}; // for parsing command-line arguments
+use num::{BigInt, BigRational};
+use num::traits::ToPrimitive;
 
-fn bins(values: &Vec<f64>, binwidth: f64, binstart: f64) -> Vec<(f64, u32)> {
-    // Input: a vector of floats and a bin width 
-    // Output: a vector of bin edges and counts for each bin
+// total_cmp_f64 implements the IEEE-754 totalOrder predicate via the usual
+// bit trick: reinterpret each float as its bit pattern, and for negative
+// values flip every bit below the sign bit so the resulting i64s compare the
+// same way the floats would under a total order (NaNs sort to the extremes,
+// negatives sort below positives). Unlike partial_cmp, this never returns
+// None, so it gives deterministic sorting even when NaN is present.
+fn total_cmp_f64(a: f64, b: f64) -> Ordering {
+    let to_ordered = |x: f64| -> i64 {
+        let bits = x.to_bits();
+        if bits & 0x8000_0000_0000_0000 != 0 {
+            (bits ^ 0x7fff_ffff_ffff_ffff) as i64
+        } else {
+            bits as i64
+        }
+    };
 
-    // unique integer identifier for each bin, to be used to check if bin is present
-    // the actual bin edges will be computed as floats, so this avoids 
-    // checking for strict float equality
-    let mut bin_ids: Vec<i32> = Vec::new();
+    to_ordered(a).cmp(&to_ordered(b))
+}
+
+// Binner accumulates values into bins one at a time, so a caller never needs
+// to hold more than the active set of bin ids in memory (as opposed to
+// buffering the whole input up front). It's also what makes the binning
+// logic reusable as a library and testable independent of stdin.
+struct Binner {
+    binwidth: f64,
+    binstart: f64,
+    // counts keyed by a unique integer identifier for each bin, so
+    // accumulating a value is an amortized O(1) map lookup instead of a
+    // linear scan. the actual bin edges are computed as floats afterwards,
+    // so this also avoids checking for strict float equality
+    counts: HashMap<i32, u32>,
+}
+
+impl Binner {
+    fn new(binwidth: f64, binstart: f64) -> Binner {
+        Binner { binwidth, binstart, counts: HashMap::new() }
+    }
+
+    fn push(&mut self, value: f64) {
+        let bin_id = ((value - self.binstart) / self.binwidth).floor() as i32;
+        *self.counts.entry(bin_id).or_insert(0) += 1;
+    }
+
+    fn finish(self) -> Vec<(f64, u32)> {
+        let (binwidth, binstart) = (self.binwidth, self.binstart);
+
+        // materialize (center, count) pairs from the bin ids
+        let mut edges_counts: Vec<(f64, u32)> = self.counts.into_iter()
+            .map(|(bin_id, count)| (binwidth * (bin_id as f64 + 0.5) + binstart, count))
+            .collect();
+
+        // sort result by edge using a total order, so the output is
+        // deterministic even if a NaN edge were ever to slip through
+        edges_counts.sort_by(|a, b| total_cmp_f64(a.0, b.0));
 
-    // computed bin edge values and counts 
-    let mut edges_counts: Vec<(f64, u32)> = Vec::new();
+        return edges_counts;
+    }
+}
+
+// parse_exact_decimal turns a decimal literal like "0.1" or "-3" into an
+// exact fraction instead of going through f64, so the `--exact` path never
+// picks up binary floating-point rounding error in the first place.
+fn parse_exact_decimal(s: &str) -> Result<BigRational, String> {
+    let s = s.trim();
+    let (neg, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+
+    // split off an optional scientific-notation exponent (e.g. "1e-1",
+    // "2.5E3") so finite values in scientific notation also parse here;
+    // "inf" and "NaN" are rejected
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(idx) => {
+            let exponent: i32 = unsigned[idx + 1..].parse()
+                .map_err(|_| format!("invalid number: {}", s))?;
+            (&unsigned[..idx], exponent)
+        },
+        None => (unsigned, 0),
+    };
+
+    let mut parts = mantissa.splitn(2, '.');
+    let int_part = parts.next().unwrap_or("");
+    let frac_part = parts.next().unwrap_or("");
+    if int_part.is_empty() && frac_part.is_empty() {
+        return Err(format!("invalid number: {}", s));
+    }
+
+    let digits = format!("{}{}", int_part, frac_part);
+    let numerator: BigInt = digits.parse().map_err(|_| format!("invalid number: {}", s))?;
+    let denominator: BigInt = BigInt::from(10).pow(frac_part.len() as u32);
+
+    let mut value = BigRational::new(numerator, denominator);
+    if exponent >= 0 {
+        value *= BigRational::from_integer(BigInt::from(10).pow(exponent as u32));
+    } else {
+        value /= BigRational::from_integer(BigInt::from(10).pow((-exponent) as u32));
+    }
+    if neg {
+        value = -value;
+    }
+    Ok(value)
+}
+
+// bins_exact mirrors `Binner`, but keys and accumulates bin ids in exact
+// rational arithmetic (see parse_exact_decimal), only converting to f64 once
+// a bin's center is computed, so repeated runs with widths like 0.1 never
+// drift across bin boundaries.
+fn bins_exact(values: &Vec<BigRational>, binwidth: &BigRational, binstart: &BigRational) -> Vec<(f64, u32)> {
+    let mut counts: HashMap<BigInt, u32> = HashMap::new();
 
     for val in values {
-        let bin_id = (val / binwidth).floor() as i32;
-
-        // check if the bin is already in the vector
-        match bin_ids.iter().position(|&b| b == bin_id) {
-            // if it is, add to the corresponding counts
-            Some(i) => { edges_counts[i].1 += 1 },
-            // else, add a new bin
-            _ => {
-                bin_ids.push(bin_id);
-                edges_counts.push(
-                    (binwidth * (((val - binstart) / 
-                      binwidth).floor() + 0.5) + binstart, 1)
-                );
-            },
-        };
-    }
-
-    // sort result by edge, then return. Since floats may contain NaN,
-    // we need to use partial_cmp since edges are floats,
-    // and specify what to do for errors
-    edges_counts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
-
-    return edges_counts;
+        let bin_id = ((val - binstart) / binwidth).floor().to_integer();
+        *counts.entry(bin_id).or_insert(0) += 1;
+    }
+
+    let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+    let mut edges_counts: Vec<(f64, u32)> = counts.into_iter()
+        .map(|(bin_id, count)| {
+            let center = binwidth * (BigRational::from_integer(bin_id) + &half) + binstart;
+            (center.to_f64().unwrap_or(f64::NAN), count)
+        })
+        .collect();
+
+    edges_counts.sort_by(|a, b| total_cmp_f64(a.0, b.0));
+
+    edges_counts
+}
+
+// render_tsv is the original, default output: tab-separated center and count,
+// one bin per line.
+fn render_tsv(result: &[(f64, u32)]) -> String {
+    result.iter()
+        .map(|(center, count)| format!("{}\t{}", center, count))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_csv(result: &[(f64, u32)]) -> String {
+    let mut lines = vec!["center,count".to_string()];
+    lines.extend(result.iter().map(|(center, count)| format!("{},{}", center, count)));
+    lines.join("\n")
+}
+
+fn render_json(result: &[(f64, u32)]) -> String {
+    let entries: Vec<String> = result.iter()
+        .map(|(center, count)| format!("{{\"center\":{},\"count\":{}}}", center, count))
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+// render_hist draws an ASCII bar chart: each line is the bin center, its
+// count, and a row of block characters scaled so the largest bin's bar is
+// exactly `width` characters wide.
+fn render_hist(result: &[(f64, u32)], width: usize) -> String {
+    let max_count = result.iter().map(|(_, count)| *count).max().unwrap_or(0);
+
+    result.iter()
+        .map(|(center, count)| {
+            let bar_len = if max_count == 0 { 0 } else { (*count as usize * width) / max_count as usize };
+            format!("{}\t{}\t{}", center, count, "\u{2588}".repeat(bar_len))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 
@@ -74,50 +205,196 @@ fn main() {
         .arg(Arg::with_name("INPUT")
              .help("Input stream")
              .index(1))
+        .arg(Arg::with_name("exact")
+             .long("exact")
+             .help("Compute bin edges with exact rational arithmetic instead of f64"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .value_name("FORMAT")
+             .help("Set output format")
+             .takes_value(true)
+             .possible_values(&["tsv", "csv", "json", "hist"])
+             .default_value("tsv"))
+        .arg(Arg::with_name("width")
+             .long("width")
+             .value_name("COLUMNS")
+             .help("Width in characters of the largest hist bar")
+             .takes_value(true)
+             .default_value("50"))
         .get_matches();
 
+    let exact = arg_matches.is_present("exact");
+
     // type-checking macro explaind in clap example 12_typed_values.rs
     let binwidth: f64 = value_t!(arg_matches, "binwidth", f64).unwrap_or(1.0);
     let binstart: f64 = value_t!(arg_matches, "binstart", f64).unwrap_or(0.0);
 
-    // parse stdin to make a list of values 
+    // binwidth/binstart feed the same floor() as i32 expression that the
+    // per-line NaN/inf check below protects, so reject non-finite values here too
+    if !binwidth.is_finite() || !binstart.is_finite() {
+        eprintln!("error: binwidth and binstart must be finite");
+        std::process::exit(1);
+    }
+
+    // a zero binwidth divides every value by zero; the f64 path would just
+    // produce inf/NaN bins, but the --exact path's BigRational division
+    // panics outright, so reject it up front for both
+    if binwidth == 0.0 {
+        eprintln!("error: binwidth must be non-zero");
+        std::process::exit(1);
+    }
+
     let stdin = io::stdin();
-    let mut values: Vec<f64> = Vec::new();
-    for line in stdin.lock().lines() {
-        let elem: f64 = match line.unwrap().trim().parse() {
-            Ok(num) => num,
-            Err(_) => {
-                eprintln!("Invalid value entered");
+    let result = if exact {
+        // re-parse the width/start strings directly, rather than going
+        // through the f64s above, so they stay exact too
+        let binwidth = parse_exact_decimal(arg_matches.value_of("binwidth").unwrap())
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            });
+        let binstart = parse_exact_decimal(arg_matches.value_of("binstart").unwrap())
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            });
+
+        let mut values: Vec<BigRational> = Vec::new();
+        for line in stdin.lock().lines() {
+            match parse_exact_decimal(line.unwrap().trim()) {
+                Ok(val) => values.push(val),
+                Err(_) => eprintln!("Invalid value entered"),
+            };
+        }
+
+        bins_exact(&values, &binwidth, &binstart)
+    } else {
+        // accumulate each value as its line is read, rather than buffering
+        // the whole input into a Vec first
+        let mut binner = Binner::new(binwidth, binstart);
+        for line in stdin.lock().lines() {
+            let elem: f64 = match line.unwrap().trim().parse() {
+                Ok(num) => num,
+                Err(_) => {
+                    eprintln!("Invalid value entered");
+                    continue;
+                },
+            };
+
+            if !elem.is_finite() {
+                eprintln!("Ignoring non-finite value (NaN/inf not supported)");
                 continue;
-            },
-        };
-        values.push(elem);
-    }
+            }
+
+            binner.push(elem);
+        }
+
+        binner.finish()
+    };
+
+    let format = arg_matches.value_of("format").unwrap();
+    let hist_width: usize = value_t!(arg_matches, "width", usize).unwrap_or(50);
 
-    // compute bins and print
-    let result = bins(&values, binwidth, binstart); 
-    for bin in &result {
-        println!("{}\t{}", bin.0, bin.1);
+    let output = match format {
+        "csv" => render_csv(&result),
+        "json" => render_json(&result),
+        "hist" => render_hist(&result, hist_width),
+        _ => render_tsv(&result),
+    };
+    if !output.is_empty() {
+        println!("{}", output);
     }
 }
 
 
 #[cfg(test)]
 mod tests{
-    use super::bins;
+    use super::{Binner, bins_exact, parse_exact_decimal, render_csv, render_hist, render_json, render_tsv, total_cmp_f64};
+    use std::cmp::Ordering;
+
+    fn bins(values: &[f64], binwidth: f64, binstart: f64) -> Vec<(f64, u32)> {
+        let mut binner = Binner::new(binwidth, binstart);
+        for &val in values {
+            binner.push(val);
+        }
+        binner.finish()
+    }
 
     #[test]
     fn sequence() {
         // bin 10 numbers. bin edges start at binwdith / 2 intervals
         let vals = vec![1.0, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0, 4.5, 5.0, 5.5];
         let out = vec![(1.5, 2), (2.5, 2), (3.5, 2), (4.5, 2), (5.5, 2)];
-        assert_eq!(bins(&vals, 1.0, 1.0), out); 
+        assert_eq!(bins(&vals, 1.0, 1.0), out);
     }
 
     #[test]
     fn unordered() {
         let vals = vec![1.0, 55.6, -15.2, 55.9];
         let out = vec![(-15.5, 1), (1.5, 1), (55.5, 2)];
-        assert_eq!(bins(&vals, 1.0, 1.0), out); 
+        assert_eq!(bins(&vals, 1.0, 1.0), out);
+    }
+
+    #[test]
+    fn push_accumulates_incrementally() {
+        let mut binner = Binner::new(1.0, 0.0);
+        binner.push(0.1);
+        binner.push(0.2);
+        binner.push(1.5);
+        assert_eq!(binner.finish(), vec![(0.5, 2), (1.5, 1)]);
+    }
+
+    #[test]
+    fn total_cmp_orders_negatives_below_positives() {
+        assert_eq!(total_cmp_f64(-1.0, 1.0), Ordering::Less);
+        assert_eq!(total_cmp_f64(-0.0, 0.0), Ordering::Less);
+    }
+
+    #[test]
+    fn total_cmp_sorts_nan_to_the_extremes() {
+        assert_eq!(total_cmp_f64(f64::NAN, f64::INFINITY), Ordering::Greater);
+        assert_eq!(total_cmp_f64(f64::NEG_INFINITY, -f64::NAN), Ordering::Greater);
+    }
+
+    #[test]
+    fn exact_bins_do_not_drift_with_tenths() {
+        // 0.1 has no exact f64 representation, but ten of them should still
+        // land in exactly one bin when parsed as exact rationals
+        let vals: Vec<_> = (0..10).map(|_| parse_exact_decimal("0.1").unwrap()).collect();
+        let binwidth = parse_exact_decimal("1.0").unwrap();
+        let binstart = parse_exact_decimal("0.0").unwrap();
+        let out = vec![(0.5, 10)];
+        assert_eq!(bins_exact(&vals, &binwidth, &binstart), out);
+    }
+
+    #[test]
+    fn parses_scientific_notation() {
+        assert_eq!(parse_exact_decimal("1e-1").unwrap(), parse_exact_decimal("0.1").unwrap());
+        assert_eq!(parse_exact_decimal("2.5E3").unwrap(), parse_exact_decimal("2500").unwrap());
+        assert_eq!(parse_exact_decimal("-1e2").unwrap(), parse_exact_decimal("-100").unwrap());
+    }
+
+    #[test]
+    fn renders_tsv() {
+        let result = vec![(1.5, 2), (2.5, 1)];
+        assert_eq!(render_tsv(&result), "1.5\t2\n2.5\t1");
+    }
+
+    #[test]
+    fn renders_csv() {
+        let result = vec![(1.5, 2), (2.5, 1)];
+        assert_eq!(render_csv(&result), "center,count\n1.5,2\n2.5,1");
+    }
+
+    #[test]
+    fn renders_json() {
+        let result = vec![(1.5, 2), (2.5, 1)];
+        assert_eq!(render_json(&result), "[{\"center\":1.5,\"count\":2},{\"center\":2.5,\"count\":1}]");
+    }
+
+    #[test]
+    fn renders_hist_scaled_to_width() {
+        let result = vec![(1.5, 2), (2.5, 4)];
+        assert_eq!(render_hist(&result, 10), "1.5\t2\t\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\n2.5\t4\t\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}");
     }
 }